@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+/// An iterator over exponentially growing retry delays.
+///
+/// Delays start at `base` and double on every step until
+/// they reach `max`. Each delay is then shortened by a
+/// random fraction of up to `jitter` of itself, so that
+/// clients that fail together don't retry in lockstep.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    next: Duration,
+    max: Duration,
+    jitter: f64,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Backoff {
+            next: base.min(max),
+            max,
+            jitter: 0.0,
+        }
+    }
+
+    /// Sets the jitter, which must be between 0 and 1.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "jitter must be between 0 and 1"
+        );
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let delay = self.next;
+        self.next = delay
+            .checked_mul(2)
+            .map_or(self.max, |next| next.min(self.max));
+        let jitter =
+            delay.mul_f64(self.jitter * fastrand::f64());
+        Some(delay - jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let delays: Vec<_> = Backoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(80),
+        )
+        .take(6)
+        .collect();
+        assert_eq!(
+            delays,
+            [10, 20, 40, 80, 80, 80]
+                .map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn test_backoff_base_above_max() {
+        let mut backoff = Backoff::new(
+            Duration::from_secs(10),
+            Duration::from_secs(1),
+        );
+        assert_eq!(
+            backoff.next(),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn test_backoff_jitter_within_bounds() {
+        let backoff = Backoff::new(
+            Duration::from_millis(100),
+            Duration::from_millis(400),
+        )
+        .with_jitter(0.5);
+        let expected =
+            [100, 200, 400, 400].map(Duration::from_millis);
+        for _ in 0..100 {
+            for (delay, expected) in
+                backoff.clone().zip(expected)
+            {
+                assert!(delay <= expected);
+                assert!(delay >= expected / 2);
+            }
+        }
+    }
+}
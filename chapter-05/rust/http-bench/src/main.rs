@@ -1,14 +1,293 @@
+mod backoff;
+
+use backoff::Backoff;
 use clap::Parser;
 use reqwest::Client;
 use std::time::{Duration, Instant};
-use std::{fs::File, io::Write};
+use std::{fs::File, io, io::Write};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Name of the person to greet
+    /// Number of nodes, listening on ports starting at 8080
     #[arg(short, long)]
     node_count: u16,
+
+    /// Number of times to retry a failed request, with
+    /// exponential backoff between attempts
+    #[arg(long, default_value_t = 0)]
+    max_retries: u32,
+
+    /// Warm up a shared connection to each node with an
+    /// unmeasured request before sampling
+    #[arg(long)]
+    preflight: bool,
+
+    /// Comma-separated relative share of requests for each
+    /// node (defaults to equal shares)
+    #[arg(long, value_delimiter = ',')]
+    weights: Vec<u32>,
+
+    /// Fail if the p50 latency exceeds this many milliseconds
+    #[arg(long)]
+    slo_p50_ms: Option<f64>,
+
+    /// Fail if the p90 latency exceeds this many milliseconds
+    #[arg(long)]
+    slo_p90_ms: Option<f64>,
+
+    /// Fail if the p99 latency exceeds this many milliseconds
+    #[arg(long)]
+    slo_p99_ms: Option<f64>,
+}
+
+/// Picks nodes in proportion to their weights.
+///
+/// Uses smooth weighted round-robin, which interleaves the
+/// picks rather than sending a burst to the heaviest node.
+/// With equal weights it is plain round-robin.
+struct WeightedSelector {
+    weights: Vec<i64>,
+    current: Vec<i64>,
+    total: i64,
+}
+
+impl WeightedSelector {
+    fn new(
+        weights: &[u32],
+        node_count: usize,
+    ) -> anyhow::Result<Self> {
+        let weights: Vec<i64> = if weights.is_empty() {
+            vec![1; node_count]
+        } else {
+            weights.iter().map(|&w| w as i64).collect()
+        };
+        if weights.len() != node_count {
+            anyhow::bail!(
+                "expected {} weights, got {}",
+                node_count,
+                weights.len()
+            );
+        }
+        let total = weights.iter().sum();
+        if total == 0 {
+            anyhow::bail!(
+                "at least one weight must be non-zero"
+            );
+        }
+        let current = vec![0; node_count];
+        Ok(WeightedSelector {
+            weights,
+            current,
+            total,
+        })
+    }
+
+    fn next(&mut self) -> usize {
+        let mut selected = 0;
+        for node_id in 0..self.weights.len() {
+            self.current[node_id] += self.weights[node_id];
+            if self.current[node_id]
+                > self.current[selected]
+            {
+                selected = node_id;
+            }
+        }
+        self.current[selected] -= self.total;
+        selected
+    }
+}
+
+/// A single latency sample.
+#[derive(Debug)]
+struct Sample {
+    index: usize,
+    /// Latency of the request, including any retries.
+    latency_secs: f64,
+    /// HTTP status code of the last attempt.
+    status: u16,
+    retries: u32,
+    /// Latency of the preflight request to the same node.
+    first_request_secs: Option<f64>,
+    /// Start of the request, in microseconds since the start
+    /// of the run.
+    start_micros: u64,
+}
+
+fn write_samples(
+    mut out: impl Write,
+    samples: &[Sample],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "Sample,Latency_secs,Status,Retries,First_request_secs,Start_micros"
+    )?;
+    for sample in samples {
+        let first_request_secs = sample
+            .first_request_secs
+            .map(|secs| secs.to_string())
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            sample.index,
+            sample.latency_secs,
+            sample.status,
+            sample.retries,
+            first_request_secs,
+            sample.start_micros
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the nearest-rank `percentile` of `sorted`, which
+/// must be non-empty and sorted in ascending order.
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    let rank = (percentile / 100.0 * sorted.len() as f64)
+        .ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Checks the sample latencies against the SLOs given on
+/// the command line and returns an error listing every
+/// violated one.
+fn check_slos(
+    args: &Args,
+    samples: &[Sample],
+) -> anyhow::Result<()> {
+    if samples.is_empty() {
+        return Ok(());
+    }
+    let mut latencies_ms: Vec<f64> = samples
+        .iter()
+        .map(|sample| sample.latency_secs * 1000.0)
+        .collect();
+    latencies_ms.sort_by(|a, b| a.total_cmp(b));
+    let slos = [
+        (50.0, args.slo_p50_ms),
+        (90.0, args.slo_p90_ms),
+        (99.0, args.slo_p99_ms),
+    ];
+    let mut violations = Vec::new();
+    for (p, slo_ms) in slos {
+        let Some(slo_ms) = slo_ms else {
+            continue;
+        };
+        let measured_ms = percentile(&latencies_ms, p);
+        if measured_ms > slo_ms {
+            violations.push(format!(
+                "p{} latency {:.3} ms exceeds SLO of {} ms",
+                p, measured_ms, slo_ms
+            ));
+        }
+    }
+    if !violations.is_empty() {
+        anyhow::bail!(
+            "SLO violated: {}",
+            violations.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Sends `num_requests` requests across `urls` according to
+/// the node weights, spaced `delay` apart, and returns their
+/// samples in order.
+async fn run(
+    args: &Args,
+    urls: &[String],
+    num_requests: usize,
+    delay: Duration,
+) -> anyhow::Result<Vec<Sample>> {
+    let mut selector =
+        WeightedSelector::new(&args.weights, urls.len())?;
+    let run_start = Instant::now();
+    // Only share a client when the preflight requests
+    // have warmed up its connections. Otherwise each
+    // request builds its own client inside its task, so
+    // that doesn't slow down the request pacing loop.
+    let shared_client = args.preflight.then(Client::new);
+    let mut first_request_secs = vec![None; urls.len()];
+    if let Some(client) = &shared_client {
+        for (node_id, url) in urls.iter().enumerate() {
+            let begin = Instant::now();
+            let response = client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?;
+            let latency = begin.elapsed();
+            // Read the body so the connection goes back to the pool.
+            response.bytes().await?;
+            first_request_secs[node_id] =
+                Some(latency.as_secs_f64());
+        }
+    }
+    let max_retries = args.max_retries;
+    let mut tasks = Vec::new();
+    for i in 0..num_requests {
+        let node_id = selector.next();
+        let url = urls[node_id].clone();
+        let first_request_secs =
+            first_request_secs[node_id];
+        let shared_client = shared_client.clone();
+        tasks.push(tokio::spawn(async move {
+            let client =
+                shared_client.unwrap_or_else(Client::new);
+            let mut retries = 0;
+            let mut backoff = Backoff::new(
+                Duration::from_millis(10),
+                Duration::from_secs(1),
+            )
+            .with_jitter(0.5);
+            let begin = Instant::now();
+            let response = loop {
+                match client.get(&url).send().await {
+                    Ok(response)
+                        if response
+                            .status()
+                            .is_success()
+                            || retries == max_retries =>
+                    {
+                        break response;
+                    }
+                    Err(err) if retries == max_retries => {
+                        return Err(err.into());
+                    }
+                    _ => {
+                        retries += 1;
+                        let delay = backoff
+                            .next()
+                            .expect("backoff never ends");
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+            };
+            let end = Instant::now();
+            let latency = end.duration_since(begin);
+            let start_micros =
+                begin.duration_since(run_start).as_micros()
+                    as u64;
+            let status = response.status();
+            // Read the body so the connection goes back to the pool.
+            response.bytes().await?;
+            anyhow::Ok(Sample {
+                index: i,
+                latency_secs: latency.as_secs_f64(),
+                status: status.as_u16(),
+                retries,
+                first_request_secs,
+                start_micros,
+            })
+        }));
+        tokio::time::sleep(delay).await;
+    }
+    let mut samples = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        samples.push(task.await??);
+    }
+    Ok(samples)
 }
 
 #[tokio::main]
@@ -24,32 +303,250 @@ async fn main() -> anyhow::Result<()> {
     }
     let num_requests = 1000;
     let delay = Duration::from_millis(10);
-    let mut tasks = Vec::new();
-    for i in 0..num_requests {
-        let url =
-            urls[i % args.node_count as usize].clone();
-        tasks.push(tokio::spawn(async move {
-            let url = url.clone();
-            let client = Client::new();
-            let begin = Instant::now();
-            let response = client.get(url).send().await?;
-            let end = Instant::now();
-            if !response.status().is_success() {
-                anyhow::bail!(
-                    "HTTP request failed: {}",
-                    response.status()
-                );
+    let samples =
+        run(&args, &urls, num_requests, delay).await?;
+    let file = File::create("latency_samples.txt")?;
+    write_samples(file, &samples)?;
+    check_slos(&args, &samples)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Starts an HTTP server that answers every request with
+    /// 200 OK and counts the requests it receives.
+    async fn start_server() -> (String, Arc<AtomicUsize>) {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/hello",
+            listener.local_addr().unwrap()
+        );
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) =
+                    listener.accept().await.unwrap();
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    while let Ok(n) =
+                        socket.read(&mut buf).await
+                    {
+                        if n == 0 {
+                            break;
+                        }
+                        counter
+                            .fetch_add(1, Ordering::SeqCst);
+                        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                        if socket
+                            .write_all(response)
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
             }
-            let latency = end.duration_since(begin);
-            Ok((i, latency.as_secs_f64()))
-        }));
-        tokio::time::sleep(delay).await;
+        });
+        (url, requests)
     }
-    let mut file = File::create("latency_samples.txt")?;
-    writeln!(file, "Sample,Latency_secs")?;
-    for task in tasks {
-        let (sample, latency) = task.await??;
-        writeln!(file, "{},{}", sample, latency)?;
+
+    #[tokio::test]
+    async fn test_preflight_excluded_from_samples() {
+        let (url, requests) = start_server().await;
+        let args = Args::parse_from([
+            "http-bench",
+            "--node-count",
+            "1",
+            "--preflight",
+        ]);
+        let samples =
+            run(&args, &[url], 3, Duration::from_millis(1))
+                .await
+                .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        let indices: Vec<_> =
+            samples.iter().map(|s| s.index).collect();
+        assert_eq!(indices, [0, 1, 2]);
+        assert!(samples
+            .iter()
+            .all(|s| s.first_request_secs.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_start_timestamps_are_ordered() {
+        let (url, _) = start_server().await;
+        let args = Args::parse_from([
+            "http-bench",
+            "--node-count",
+            "1",
+        ]);
+        let samples =
+            run(&args, &[url], 5, Duration::from_millis(1))
+                .await
+                .unwrap();
+        let mut out = Vec::new();
+        write_samples(&mut out, &samples).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let timestamps: Vec<u64> = out
+            .lines()
+            .skip(1)
+            .map(|line| {
+                line.rsplit(',')
+                    .next()
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(timestamps.len(), 5);
+        assert!(timestamps
+            .windows(2)
+            .all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_weighted_selector() {
+        let mut selector =
+            WeightedSelector::new(&[1, 2, 1], 3).unwrap();
+        let mut counts = [0; 3];
+        for _ in 0..400 {
+            counts[selector.next()] += 1;
+        }
+        assert_eq!(counts, [100, 200, 100]);
+    }
+
+    #[test]
+    fn test_weighted_selector_defaults_to_round_robin() {
+        let mut selector =
+            WeightedSelector::new(&[], 3).unwrap();
+        let picks: Vec<_> =
+            (0..6).map(|_| selector.next()).collect();
+        assert_eq!(picks, [0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_weighted_selector_validates_weights() {
+        assert!(WeightedSelector::new(&[1, 2], 3).is_err());
+        assert!(WeightedSelector::new(&[0, 0], 2).is_err());
+    }
+
+    fn samples_with_latencies(
+        latencies_ms: &[f64],
+    ) -> Vec<Sample> {
+        latencies_ms
+            .iter()
+            .enumerate()
+            .map(|(index, latency_ms)| Sample {
+                index,
+                latency_secs: latency_ms / 1000.0,
+                status: 200,
+                retries: 0,
+                first_request_secs: None,
+                start_micros: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<f64> =
+            (1..=100).map(|i| i as f64).collect();
+        assert_eq!(percentile(&sorted, 50.0), 50.0);
+        assert_eq!(percentile(&sorted, 99.0), 99.0);
+        assert_eq!(percentile(&sorted, 100.0), 100.0);
+        assert_eq!(percentile(&[7.0], 50.0), 7.0);
+    }
+
+    #[test]
+    fn test_check_slos() {
+        let latencies_ms: Vec<f64> =
+            (1..=100).map(|i| i as f64).collect();
+        let samples = samples_with_latencies(&latencies_ms);
+        let args = Args::parse_from([
+            "http-bench",
+            "--node-count",
+            "1",
+            "--slo-p50-ms",
+            "60",
+            "--slo-p99-ms",
+            "100",
+        ]);
+        assert!(check_slos(&args, &samples).is_ok());
+        let args = Args::parse_from([
+            "http-bench",
+            "--node-count",
+            "1",
+            "--slo-p50-ms",
+            "60",
+            "--slo-p90-ms",
+            "10",
+            "--slo-p99-ms",
+            "1",
+        ]);
+        let err = check_slos(&args, &samples)
+            .unwrap_err()
+            .to_string();
+        assert!(!err.contains("p50"));
+        assert!(err.contains("p90 latency 90.000 ms"));
+        assert!(err.contains("p99 latency 99.000 ms"));
+    }
+
+    #[test]
+    fn test_write_samples() {
+        let samples = vec![
+            Sample {
+                index: 0,
+                latency_secs: 0.5,
+                status: 200,
+                retries: 0,
+                first_request_secs: Some(0.75),
+                start_micros: 100,
+            },
+            Sample {
+                index: 1,
+                latency_secs: 1.25,
+                status: 503,
+                retries: 2,
+                first_request_secs: None,
+                start_micros: 10100,
+            },
+        ];
+        let mut out = Vec::new();
+        write_samples(&mut out, &samples).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        let header: Vec<_> =
+            lines.next().unwrap().split(',').collect();
+        assert_eq!(
+            header,
+            [
+                "Sample",
+                "Latency_secs",
+                "Status",
+                "Retries",
+                "First_request_secs",
+                "Start_micros"
+            ]
+        );
+        let rows: Vec<Vec<_>> = lines
+            .map(|line| line.split(',').collect())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ["0", "0.5", "200", "0", "0.75", "100"],
+                ["1", "1.25", "503", "2", "", "10100"]
+            ]
+        );
     }
-    Ok(())
 }
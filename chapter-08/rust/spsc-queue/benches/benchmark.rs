@@ -1,13 +1,20 @@
 use criterion::{criterion_group, criterion_main, Criterion};
+use hdrhistogram::Histogram;
 use pprof::criterion::{Output, PProfProfiler};
 use spsc_queue::queue::Queue;
 use spsc_queue::spsc_queue::SpscQueue;
-use std::sync::{Arc, Mutex, RwLock};
+use std::env;
+use std::hint;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Instant;
 
 fn bench(c: &mut Criterion) {
     queue_bench(c);
     spsc_bench(c);
+    if env::var_os("SPSC_QUEUE_BENCH_LATENCY").is_some() {
+        spsc_latency_bench(c);
+    }
 }
 
 fn queue_bench(c: &mut Criterion) {
@@ -48,6 +55,67 @@ fn spsc_bench(c: &mut Criterion) {
     });
 }
 
+/// Records push-to-pop latencies of timestamped items and prints their
+/// percentiles. Runs only when `SPSC_QUEUE_BENCH_LATENCY` is set.
+///
+/// The histogram is reset at the start of every batch, so the percentiles
+/// come from the last criterion sample alone rather than from all of them.
+fn spsc_latency_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spsc-queue-latency-bench");
+    let mut histogram = Histogram::<u64>::new(3).unwrap();
+    group.bench_function("SpscQueue::push()+pop() latency", |b| {
+        b.iter_custom(|iters| {
+            // Only keep the last batch, so that warm-up iterations don't
+            // skew the percentiles.
+            histogram.reset();
+            let consumer_queue = Arc::new(SpscQueue::<u64, 128>::new());
+            let producer_queue = consumer_queue.clone();
+            let epoch = Instant::now();
+            let producer_thread = thread::spawn(move || {
+                for _ in 0..iters {
+                    // Timestamp each attempt so that time spent waiting
+                    // for a free slot is not counted as queueing latency.
+                    loop {
+                        let timestamp = epoch.elapsed().as_nanos() as u64;
+                        if producer_queue.push(timestamp).is_ok() {
+                            break;
+                        }
+                        hint::spin_loop();
+                    }
+                }
+            });
+            for _ in 0..iters {
+                let timestamp = loop {
+                    if let Some(timestamp) = consumer_queue.pop() {
+                        break timestamp;
+                    }
+                    hint::spin_loop();
+                };
+                let latency = epoch.elapsed().as_nanos() as u64 - timestamp;
+                histogram.record(latency).unwrap();
+            }
+            producer_thread.join().unwrap();
+            epoch.elapsed()
+        });
+    });
+    group.finish();
+    if histogram.is_empty() {
+        return;
+    }
+    println!(
+        "SpscQueue push-to-pop latency (ns, {} items from the last criterion sample only):",
+        histogram.len()
+    );
+    for percentile in [50.0, 90.0, 99.0, 99.9] {
+        println!(
+            "  p{:<5} {}",
+            percentile,
+            histogram.value_at_percentile(percentile)
+        );
+    }
+    println!("  max    {}", histogram.max());
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
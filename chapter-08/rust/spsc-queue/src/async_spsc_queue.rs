@@ -0,0 +1,138 @@
+use crate::spsc_queue::SpscQueue;
+use std::future::poll_fn;
+use std::sync::atomic::{fence, AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+/// A single-producer, single-consumer queue whose consumer can await items.
+///
+/// When the queue is empty, `recv` registers the consumer's `Waker`, and the
+/// next `push` wakes it up, so the consumer doesn't have to busy-wait. The
+/// producer only takes the waker lock when a consumer is actually waiting.
+///
+/// Only one task may call `recv` at a time: a second consumer would replace
+/// the first one's waker and the first one would never be woken up.
+pub struct AsyncSpscQueue<T: Default + Copy, const N: usize> {
+    queue: SpscQueue<T, N>,
+    waker: Mutex<Option<Waker>>,
+    waiting: AtomicBool,
+}
+
+impl<T: Default + Copy, const N: usize> AsyncSpscQueue<T, N> {
+    /// Create a new queue.
+    pub fn new() -> Self {
+        let queue = SpscQueue::new();
+        let waker = Mutex::new(None);
+        let waiting = AtomicBool::new(false);
+        AsyncSpscQueue {
+            queue,
+            waker,
+            waiting,
+        }
+    }
+
+    /// Pushes an item into the queue and wakes up a waiting consumer. Returns
+    /// an error if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)?;
+        // Pairs with the fence in `poll_recv`: either we see the waiting flag
+        // here, or the consumer's re-check sees the item we just pushed.
+        fence(Ordering::SeqCst);
+        if self.waiting.load(Ordering::Relaxed) {
+            let waker = {
+                let mut waker = self.waker.lock().unwrap();
+                self.waiting.store(false, Ordering::Relaxed);
+                waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+        Ok(())
+    }
+
+    /// Pops an item from the queue. Returns `None` if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    /// Pops an item from the queue, waiting for one to be pushed if the queue
+    /// is empty.
+    pub async fn recv(&self) -> T {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<T> {
+        if let Some(value) = self.queue.pop() {
+            return Poll::Ready(value);
+        }
+        {
+            let mut waker = self.waker.lock().unwrap();
+            *waker = Some(cx.waker().clone());
+            self.waiting.store(true, Ordering::Relaxed);
+        }
+        fence(Ordering::SeqCst);
+        // The producer may have pushed before it could see the waiting flag.
+        match self.queue.pop() {
+            Some(value) => {
+                self.waiting.store(false, Ordering::Relaxed);
+                Poll::Ready(value)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T: Default + Copy, const N: usize> Default for AsyncSpscQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_async_spsc_queue_recv() {
+        let queue = AsyncSpscQueue::<i32, 4>::new();
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.recv().await, 1);
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[tokio::test]
+    async fn test_async_spsc_queue_push_after_recv() {
+        let queue = Arc::new(AsyncSpscQueue::<i32, 4>::new());
+        let consumer_queue = queue.clone();
+        let consumer = tokio::spawn(async move { consumer_queue.recv().await });
+        // Let the consumer run until it is waiting on the empty queue.
+        tokio::task::yield_now().await;
+        assert!(!consumer.is_finished());
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(consumer.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_async_spsc_queue_no_lost_wakeups() {
+        const ITEMS: i32 = 10_000;
+        let queue = Arc::new(AsyncSpscQueue::<i32, 4>::new());
+        let producer_queue = queue.clone();
+        let producer = std::thread::spawn(move || {
+            for i in 0..ITEMS {
+                while producer_queue.push(i).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        for i in 0..ITEMS {
+            let value = tokio::time::timeout(Duration::from_secs(10), queue.recv())
+                .await
+                .expect("consumer was never woken up");
+            assert_eq!(value, i);
+        }
+        producer.join().unwrap();
+    }
+}
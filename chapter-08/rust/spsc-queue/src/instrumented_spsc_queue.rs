@@ -0,0 +1,141 @@
+use crate::spsc_queue::SpscQueue;
+use std::sync::atomic::{AtomicIsize, AtomicU64, Ordering};
+
+/// An `SpscQueue` that records its occupancy on every successful push and pop.
+///
+/// Occupancy stays near zero when the consumer keeps up with the producer and
+/// near `N` when the producer is faster. Samples are counted per queue length,
+/// so the histogram is exact. Failed pushes and pops are not sampled, so a
+/// consumer polling an empty queue doesn't skew the histogram towards zero.
+///
+/// Occupancy is tracked by the wrapper itself, so the underlying queue stays
+/// unmodified. The counter is updated after the push or pop completes, so the
+/// consumer can briefly see it lag behind the queue.
+pub struct InstrumentedSpscQueue<T: Default + Copy, const N: usize> {
+    queue: SpscQueue<T, N>,
+    occupancy: AtomicIsize,
+    histogram: Vec<AtomicU64>,
+}
+
+impl<T: Default + Copy, const N: usize> InstrumentedSpscQueue<T, N> {
+    /// Create a new queue.
+    pub fn new() -> Self {
+        let queue = SpscQueue::new();
+        let occupancy = AtomicIsize::new(0);
+        let histogram = (0..=N).map(|_| AtomicU64::new(0)).collect();
+        InstrumentedSpscQueue {
+            queue,
+            occupancy,
+            histogram,
+        }
+    }
+
+    /// Pushes an item into the queue. Returns an error if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        self.queue.push(value)?;
+        let occupancy = self.occupancy.fetch_add(1, Ordering::Relaxed) + 1;
+        self.record_occupancy(occupancy);
+        Ok(())
+    }
+
+    /// Pops an item from the queue. Returns `None` if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        let value = self.queue.pop()?;
+        let occupancy = self.occupancy.fetch_sub(1, Ordering::Relaxed) - 1;
+        self.record_occupancy(occupancy);
+        Some(value)
+    }
+
+    /// Returns the number of occupancy samples recorded.
+    pub fn samples(&self) -> u64 {
+        self.histogram
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Returns the smallest occupancy that at least `percentile` percent of
+    /// the samples are less than or equal to, or `None` if nothing has been
+    /// recorded yet.
+    pub fn occupancy_percentile(&self, percentile: f64) -> Option<usize> {
+        let samples = self.samples();
+        if samples == 0 {
+            return None;
+        }
+        let rank = ((percentile / 100.0 * samples as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (occupancy, count) in self.histogram.iter().enumerate() {
+            seen += count.load(Ordering::Relaxed);
+            if seen >= rank {
+                return Some(occupancy);
+            }
+        }
+        Some(N)
+    }
+
+    fn record_occupancy(&self, occupancy: isize) {
+        // The consumer may pop an item before the producer has counted it,
+        // which briefly takes the counter out of the 0..=N range.
+        let occupancy = occupancy.clamp(0, N as isize) as usize;
+        self.histogram[occupancy].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: Default + Copy, const N: usize> Default for InstrumentedSpscQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_occupancy() {
+        let queue = InstrumentedSpscQueue::<i32, 4>::new();
+        // Occupancy after each operation: 1, 2, 3, 2, 1, 0.
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.push(3), Ok(()));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.samples(), 6);
+        assert_eq!(queue.occupancy_percentile(0.0), Some(0));
+        assert_eq!(queue.occupancy_percentile(50.0), Some(1));
+        assert_eq!(queue.occupancy_percentile(90.0), Some(3));
+        assert_eq!(queue.occupancy_percentile(100.0), Some(3));
+    }
+
+    #[test]
+    fn test_occupancy_without_samples() {
+        let queue = InstrumentedSpscQueue::<i32, 4>::new();
+        assert_eq!(queue.samples(), 0);
+        assert_eq!(queue.occupancy_percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_occupancy_ignores_failed_pop() {
+        let queue = InstrumentedSpscQueue::<i32, 4>::new();
+        for _ in 0..100 {
+            assert_eq!(queue.pop(), None);
+        }
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.samples(), 2);
+        assert_eq!(queue.occupancy_percentile(50.0), Some(1));
+    }
+
+    #[test]
+    fn test_occupancy_when_full() {
+        let queue = InstrumentedSpscQueue::<i32, 2>::new();
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.push(3), Err(3));
+        assert_eq!(queue.push(3), Err(3));
+        assert_eq!(queue.samples(), 2);
+        assert_eq!(queue.occupancy_percentile(50.0), Some(1));
+        assert_eq!(queue.occupancy_percentile(100.0), Some(2));
+    }
+}
@@ -1,2 +1,6 @@
+pub mod async_spsc_queue;
+pub mod instrumented_spsc_queue;
+pub mod pool;
 pub mod queue;
+pub mod ring_buffer;
 pub mod spsc_queue;
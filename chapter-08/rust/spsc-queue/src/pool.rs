@@ -0,0 +1,91 @@
+use crate::spsc_queue::SpscQueue;
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+
+/// A fixed-capacity object pool.
+///
+/// The pool owns `N` objects up front and keeps the indices of the free ones
+/// in a queue, so acquiring and releasing an object never allocates. Objects
+/// are handed back as-is when released, so their state carries over to the
+/// next user.
+pub struct Pool<T, const N: usize> {
+    objects: [UnsafeCell<T>; N],
+    free: SpscQueue<usize, N>,
+}
+
+impl<T: Default, const N: usize> Pool<T, N> {
+    /// Create a new pool of `N` default-initialized objects.
+    pub fn new() -> Self {
+        let objects = std::array::from_fn(|_| UnsafeCell::new(T::default()));
+        let free = SpscQueue::new();
+        for index in 0..N {
+            // The queue has room for exactly `N` indices.
+            let _ = free.push(index);
+        }
+        Pool { objects, free }
+    }
+}
+
+impl<T, const N: usize> Pool<T, N> {
+    /// Takes an object out of the pool. Returns `None` if all objects are in
+    /// use.
+    pub fn acquire(&self) -> Option<PooledRef<'_, T, N>> {
+        let index = self.free.pop()?;
+        Some(PooledRef { pool: self, index })
+    }
+}
+
+impl<T: Default, const N: usize> Default for Pool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An object borrowed from a `Pool`, returned to the pool on drop.
+pub struct PooledRef<'a, T, const N: usize> {
+    pool: &'a Pool<T, N>,
+    index: usize,
+}
+
+impl<T, const N: usize> Deref for PooledRef<'_, T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // The index was popped from the free queue, so this is the only
+        // reference to the object.
+        unsafe { &*self.pool.objects[self.index].get() }
+    }
+}
+
+impl<T, const N: usize> DerefMut for PooledRef<'_, T, N> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.pool.objects[self.index].get() }
+    }
+}
+
+impl<T, const N: usize> Drop for PooledRef<'_, T, N> {
+    fn drop(&mut self) {
+        // At most `N` indices are ever in the queue, so this cannot fail.
+        let _ = self.pool.free.push(self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool() {
+        let pool = Pool::<Vec<u8>, 2>::new();
+        let mut first = pool.acquire().unwrap();
+        let second = pool.acquire().unwrap();
+        assert!(pool.acquire().is_none());
+        first.extend_from_slice(b"hello");
+        drop(first);
+        let reused = pool.acquire().unwrap();
+        assert_eq!(&*reused, b"hello");
+        assert!(pool.acquire().is_none());
+        drop(second);
+        assert!(pool.acquire().is_some());
+    }
+}
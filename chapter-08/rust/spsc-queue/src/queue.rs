@@ -13,6 +13,12 @@ impl<T: Default + Copy, const N: usize> Queue<T, N> {
         Queue { data, front, back }
     }
 
+    /// Resets the queue to its initial empty state.
+    pub fn clear(&mut self) {
+        self.front = 0;
+        self.back = 0;
+    }
+
     pub fn push(&mut self, value: T) -> Result<(), T> {
         if self.front + N - self.back == 0 {
             return Err(value);
@@ -32,6 +38,12 @@ impl<T: Default + Copy, const N: usize> Queue<T, N> {
     }
 }
 
+impl<T: Default + Copy, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -51,4 +63,22 @@ mod tests {
         assert_eq!(queue.pop(), Some(4));
         assert_eq!(queue.pop(), None);
     }
+
+    #[test]
+    fn test_sync_queue_clear() {
+        let mut queue = Queue::<i32, 4>::new();
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.pop(), Some(1));
+        queue.clear();
+        assert_eq!(queue.pop(), None);
+        for i in 0..4 {
+            assert_eq!(queue.push(i), Ok(()));
+        }
+        assert_eq!(queue.push(4), Err(4));
+        for i in 0..4 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+    }
 }
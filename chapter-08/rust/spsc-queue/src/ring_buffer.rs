@@ -0,0 +1,82 @@
+/// A bounded ring buffer that overwrites the oldest element when full.
+pub struct OverwritingRingBuffer<T: Default + Copy, const N: usize> {
+    data: [T; N],
+    front: usize,
+    back: usize,
+}
+
+impl<T: Default + Copy, const N: usize> OverwritingRingBuffer<T, N> {
+    pub fn new() -> Self {
+        let data = [T::default(); N];
+        let front = 0;
+        let back = 0;
+        OverwritingRingBuffer { data, front, back }
+    }
+
+    /// Pushes an item into the buffer, overwriting the oldest item if the
+    /// buffer is full.
+    pub fn push(&mut self, value: T) {
+        if self.back - self.front == N {
+            self.front += 1;
+        }
+        self.data[self.back % N] = value;
+        self.back += 1;
+    }
+
+    /// Pops the oldest item from the buffer. Returns `None` if the buffer is
+    /// empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.back - self.front == 0 {
+            return None;
+        }
+        let value = self.data[self.front % N];
+        self.front += 1;
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.back - self.front
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Default + Copy, const N: usize> Default for OverwritingRingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut buffer = OverwritingRingBuffer::<i32, 4>::new();
+        assert_eq!(buffer.pop(), None);
+        buffer.push(1);
+        buffer.push(2);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_ring_buffer_overwrite() {
+        let mut buffer = OverwritingRingBuffer::<i32, 4>::new();
+        for i in 0..7 {
+            buffer.push(i);
+        }
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), Some(4));
+        assert_eq!(buffer.pop(), Some(5));
+        assert_eq!(buffer.pop(), Some(6));
+        assert_eq!(buffer.pop(), None);
+    }
+}
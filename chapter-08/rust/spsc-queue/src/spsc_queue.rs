@@ -1,7 +1,24 @@
-///! Reference:
-///
-///! Nhat Minh Leˆ et al. (2013) "Correct and Efficient Bounded FIFO Queues". IEEE SBAC-PAD.
+//! Reference:
+//!
+//! Nhat Minh Leˆ et al. (2013) "Correct and Efficient Bounded FIFO Queues". IEEE SBAC-PAD.
+use std::hint;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Spins for an exponentially growing number of iterations, then starts
+/// yielding the thread once spinning gets long.
+fn backoff(step: &mut u32) {
+    const SPIN_LIMIT: u32 = 6;
+    if *step <= SPIN_LIMIT {
+        for _ in 0..(1 << *step) {
+            hint::spin_loop();
+        }
+        *step += 1;
+    } else {
+        thread::yield_now();
+    }
+}
 
 // A bounded, wait-free, single-producer, single-consumer queue.
 pub struct SpscQueue<T: Default + Copy, const N: usize> {
@@ -23,6 +40,15 @@ impl<T: Default + Copy, const N: usize> SpscQueue<T, N> {
         SpscQueue { data, front, back }
     }
 
+    /// Resets the queue to its initial empty state.
+    ///
+    /// Takes `&mut self`, so no producer or consumer may access the queue
+    /// concurrently while it is being reset.
+    pub fn reset(&mut self) {
+        *self.front.get_mut() = 0;
+        *self.back.get_mut() = 0;
+    }
+
     /// Pushes an item into the queue. Returns an error if the queue is full.
     pub fn push(&self, value: T) -> Result<(), T> {
         let back = self.back.load(Ordering::Relaxed);
@@ -38,6 +64,25 @@ impl<T: Default + Copy, const N: usize> SpscQueue<T, N> {
         Ok(())
     }
 
+    /// Pushes an item into the queue and returns the number of free slots left
+    /// after the push. Returns an error if the queue is full.
+    ///
+    /// The consumer may pop concurrently, so the returned capacity is a lower
+    /// bound.
+    pub fn push_with_capacity(&self, value: T) -> Result<usize, T> {
+        let back = self.back.load(Ordering::Relaxed);
+        let front = self.front.load(Ordering::Acquire);
+        if front + N - back == 0 {
+            return Err(value);
+        }
+        let ptr = self.data.as_ptr() as *mut T;
+        unsafe {
+            ptr.add(back % N).write(value);
+        }
+        self.back.store(back + 1, Ordering::Release);
+        Ok(front + N - (back + 1))
+    }
+
     /// Pops an item from the queue. Returns `None` if the queue is empty.
     pub fn pop(&self) -> Option<T> {
         let front = self.front.load(Ordering::Relaxed);
@@ -49,11 +94,54 @@ impl<T: Default + Copy, const N: usize> SpscQueue<T, N> {
         self.front.store(front + 1, Ordering::Release);
         Some(value)
     }
+
+    /// Pushes an item into the queue, waiting up to `timeout` for a free slot.
+    /// Returns an error if the queue is still full at the deadline.
+    pub fn push_timeout(&self, value: T, timeout: Duration) -> Result<(), T> {
+        // A timeout too large to represent means there is no deadline.
+        let deadline = Instant::now().checked_add(timeout);
+        let mut step = 0;
+        let mut value = value;
+        loop {
+            match self.push(value) {
+                Ok(()) => return Ok(()),
+                Err(v) => value = v,
+            }
+            if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                return Err(value);
+            }
+            backoff(&mut step);
+        }
+    }
+
+    /// Pops an item from the queue, waiting up to `timeout` for one to arrive.
+    /// Returns `None` if the queue is still empty at the deadline.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        // A timeout too large to represent means there is no deadline.
+        let deadline = Instant::now().checked_add(timeout);
+        let mut step = 0;
+        loop {
+            if let Some(value) = self.pop() {
+                return Some(value);
+            }
+            if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                return None;
+            }
+            backoff(&mut step);
+        }
+    }
+}
+
+impl<T: Default + Copy, const N: usize> Default for SpscQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
 
     #[test]
     fn test_spsc_queue() {
@@ -70,4 +158,84 @@ mod tests {
         assert_eq!(queue.pop(), Some(4));
         assert_eq!(queue.pop(), None);
     }
+
+    #[test]
+    fn test_spsc_queue_push_with_capacity() {
+        let queue = SpscQueue::<i32, 4>::new();
+        assert_eq!(queue.push_with_capacity(1), Ok(3));
+        assert_eq!(queue.push_with_capacity(2), Ok(2));
+        assert_eq!(queue.push_with_capacity(3), Ok(1));
+        assert_eq!(queue.push_with_capacity(4), Ok(0));
+        assert_eq!(queue.push_with_capacity(5), Err(5));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.push_with_capacity(5), Ok(0));
+    }
+
+    #[test]
+    fn test_spsc_queue_pop_timeout() {
+        let consumer_queue = Arc::new(SpscQueue::<i32, 4>::new());
+        let producer_queue = consumer_queue.clone();
+        let producer_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            producer_queue.push(1).unwrap();
+        });
+        assert_eq!(consumer_queue.pop_timeout(Duration::from_secs(10)), Some(1));
+        producer_thread.join().unwrap();
+        let begin = Instant::now();
+        assert_eq!(consumer_queue.pop_timeout(Duration::from_millis(10)), None);
+        assert!(begin.elapsed() >= Duration::from_millis(10));
+        // An item that arrives after the deadline is left in the queue.
+        let producer_queue = consumer_queue.clone();
+        let producer_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            producer_queue.push(2).unwrap();
+        });
+        assert_eq!(consumer_queue.pop_timeout(Duration::from_millis(10)), None);
+        producer_thread.join().unwrap();
+        assert_eq!(consumer_queue.pop(), Some(2));
+        // A timeout that overflows `Instant` waits without a deadline.
+        assert_eq!(consumer_queue.push_timeout(3, Duration::MAX), Ok(()));
+        assert_eq!(consumer_queue.pop_timeout(Duration::MAX), Some(3));
+    }
+
+    #[test]
+    fn test_spsc_queue_push_timeout() {
+        let producer_queue = Arc::new(SpscQueue::<i32, 1>::new());
+        let consumer_queue = producer_queue.clone();
+        assert_eq!(producer_queue.push(1), Ok(()));
+        let begin = Instant::now();
+        assert_eq!(
+            producer_queue.push_timeout(2, Duration::from_millis(10)),
+            Err(2)
+        );
+        assert!(begin.elapsed() >= Duration::from_millis(10));
+        let consumer_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            consumer_queue.pop()
+        });
+        assert_eq!(
+            producer_queue.push_timeout(2, Duration::from_secs(10)),
+            Ok(())
+        );
+        assert_eq!(consumer_thread.join().unwrap(), Some(1));
+        assert_eq!(producer_queue.pop(), Some(2));
+    }
+
+    #[test]
+    fn test_spsc_queue_reset() {
+        let mut queue = SpscQueue::<i32, 4>::new();
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.pop(), Some(1));
+        queue.reset();
+        assert_eq!(queue.pop(), None);
+        for i in 0..4 {
+            assert_eq!(queue.push(i), Ok(()));
+        }
+        assert_eq!(queue.push(4), Err(4));
+        for i in 0..4 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+    }
 }